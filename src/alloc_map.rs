@@ -0,0 +1,222 @@
+use std::fmt::Write as _;
+
+use crate::{Header, Headers, bytes::Bytes, error::FormError};
+
+/// The reconstructed kind of a byte span in a `form` image's data region.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) enum SpanKind {
+    Alloc,
+    Slack,
+    KnownFreed,
+    Unknown,
+}
+
+impl SpanKind {
+    fn short(self) -> char {
+        match self {
+            SpanKind::Alloc => 'a',
+            SpanKind::Slack => 's',
+            SpanKind::KnownFreed => 'f',
+            SpanKind::Unknown => 'u',
+        }
+    }
+}
+
+/// One contiguous span of the data region, as reconstructed from headers.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct Span {
+    pub(crate) offset: u16,
+    pub(crate) len: u16,
+    pub(crate) kind: SpanKind,
+    /// The bytes covered by this span, clipped to the bounds of the image
+    /// (a `KnownFreed` span's declared capacity may run past the image end).
+    pub(crate) bytes: Vec<u8>,
+}
+
+/// The reconstructed layout of a `form` image's data region: an ordered,
+/// non-overlapping sequence of spans covering every byte from the end of the
+/// headers area to the end of the image.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct AllocationMap {
+    pub(crate) spans: Vec<Span>,
+}
+
+impl AllocationMap {
+    /// Builds the map from parsed `headers`, filling the gaps between
+    /// allocated and freed spans with `Unknown` spans.
+    pub(crate) fn from_headers(
+        headers: &Headers,
+        form: &[u8],
+        headers_size: u16,
+    ) -> Result<AllocationMap, FormError> {
+        let form_len: u16 = form.len().try_into().unwrap();
+
+        let mut allocs = Vec::new();
+        for header in &headers.headers[..headers.used] {
+            match *header {
+                Header::Alloc {
+                    ptr, len, capacity, ..
+                } => {
+                    allocs.push((ptr, ptr + len, SpanKind::Alloc));
+                    if len != capacity {
+                        allocs.push((ptr + len, ptr + capacity, SpanKind::Slack));
+                    }
+                }
+                Header::Freed { ptr, capacity, .. } => {
+                    allocs.push((ptr, ptr + capacity, SpanKind::KnownFreed));
+                }
+                Header::Unused { .. } => unreachable!(),
+            }
+        }
+        allocs.sort();
+
+        let mut prev_alloc = headers_size;
+        for i in 0..allocs.len() {
+            let (start, end, _) = allocs[i];
+            if start > prev_alloc {
+                allocs.push((prev_alloc, start, SpanKind::Unknown));
+            }
+            if start < prev_alloc {
+                return Err(FormError::OverlappingAllocations {
+                    a: prev_alloc,
+                    b: start,
+                });
+            }
+            prev_alloc = end;
+        }
+        if prev_alloc < form_len {
+            allocs.push((prev_alloc, form_len, SpanKind::Unknown));
+        }
+        allocs.sort();
+
+        let spans = allocs
+            .into_iter()
+            .map(|(start, end, kind)| {
+                let i = start.min(form_len) as usize;
+                let j = end.min(form_len) as usize;
+                Span {
+                    offset: start,
+                    len: end - start,
+                    kind,
+                    bytes: form[i..j].to_vec(),
+                }
+            })
+            .collect();
+
+        Ok(AllocationMap { spans })
+    }
+
+    /// Renders the freed-text annotation: each span bracketed by its offset
+    /// and one-letter kind, with live allocation bytes redacted to U+FFFD.
+    pub(crate) fn freed_text(&self) -> String {
+        let mut out = String::new();
+        for span in &self.spans {
+            write!(out, "«{}:{}»", span.offset, span.kind.short()).unwrap();
+            if span.kind == SpanKind::Alloc {
+                for _ in 0..span.len {
+                    out.push('\u{FFFD}');
+                }
+            } else {
+                write!(out, "{}", Bytes(&span.bytes)).unwrap();
+            }
+        }
+        out
+    }
+
+    /// The bytes recovered for every non-allocated span, indexed by offset
+    /// into the image; `None` where nothing was recovered at that offset.
+    fn freed_cells(&self, form_len: usize) -> Vec<Option<u8>> {
+        let mut cells = vec![None; form_len];
+        for span in &self.spans {
+            if span.kind == SpanKind::Alloc {
+                continue;
+            }
+            for (k, &b) in span.bytes.iter().enumerate() {
+                cells[span.offset as usize + k] = Some(b);
+            }
+        }
+        cells
+    }
+
+    /// Checks each `(start, string)` residual string against the bytes
+    /// recovered in freed/slack/unknown spans.
+    pub(crate) fn check_residual_strings(
+        &self,
+        form: &[u8],
+        residual_strings: &[(usize, String)],
+    ) -> Result<(), FormError> {
+        let freed_cells = self.freed_cells(form.len());
+        for (start, string) in residual_strings {
+            for (i, &b) in string.as_bytes().iter().enumerate() {
+                if let Some(b2) = freed_cells[start + i]
+                    && b2 != b
+                {
+                    return Err(FormError::ResidualMismatch {
+                        start: *start,
+                        offset: i,
+                        expected: b,
+                        got: b2,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overlapping_allocations_are_rejected() {
+        let headers = Headers {
+            headers: vec![
+                Header::Alloc {
+                    ptr: 24,
+                    len: 8,
+                    capacity: 8,
+                    read: 24,
+                },
+                Header::Alloc {
+                    ptr: 28,
+                    len: 4,
+                    capacity: 4,
+                    read: 28,
+                },
+            ],
+            used: 2,
+            free_chains: Vec::new(),
+        };
+        let form = vec![0u8; 32];
+        assert!(matches!(
+            AllocationMap::from_headers(&headers, &form, 24),
+            Err(FormError::OverlappingAllocations { a: 32, b: 28 })
+        ));
+    }
+
+    #[test]
+    fn residual_mismatch_is_rejected() {
+        let map = AllocationMap {
+            spans: vec![Span {
+                offset: 0,
+                len: 4,
+                kind: SpanKind::Unknown,
+                bytes: b"abcd".to_vec(),
+            }],
+        };
+        let residual_strings = [(0, "abXd".to_string())];
+        assert!(matches!(
+            map.check_residual_strings(&[0u8; 4], &residual_strings),
+            Err(FormError::ResidualMismatch {
+                start: 0,
+                offset: 2,
+                expected: b'X',
+                got: b'c',
+            })
+        ));
+    }
+}
@@ -0,0 +1,29 @@
+use std::fmt::{self, Write};
+
+pub(crate) struct Bytes<'a>(pub(crate) &'a [u8]);
+
+impl fmt::Debug for Bytes<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\"{self}\"")
+    }
+}
+
+impl fmt::Display for Bytes<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for &b in self.0 {
+            match b {
+                b'\\' => f.write_str("\\\\"),
+                b' '..=b'~' => f.write_char(b as char),
+                b'\0' => f.write_str("\\0"),
+                // 0x08 => f.write_str("\\b"),
+                b'\t' => f.write_str("\\t"),
+                b'\n' => f.write_str("\\n"),
+                // 0x0B => f.write_str("\\v"),
+                // 0x0C => f.write_str("\\f"),
+                // 0x0D => f.write_str("\\r"),
+                b => write!(f, "\\x{b:02x}"),
+            }?;
+        }
+        Ok(())
+    }
+}
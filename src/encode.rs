@@ -0,0 +1,33 @@
+use crate::{Headers, profile::FormProfile};
+
+/// Re-assembles a byte-exact `form.m` image from parsed `headers`: the
+/// inverse of `Headers::from_form`. `data` is the data region, i.e. the
+/// image bytes starting at `profile.data_base`.
+pub(crate) fn encode_form(headers: &Headers, profile: &FormProfile, data: &[u8]) -> Vec<u8> {
+    let raw = headers.to_raw(profile);
+    let mut out = raw.to_le_bytes(profile);
+    out.extend_from_slice(data);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn round_trips_form_m() {
+        let form = fs::read("distr/form.m").unwrap();
+        let profile = FormProfile::V5;
+        let headers = Headers::from_form(&profile, &form).unwrap();
+        let data = &form[profile.headers_size as usize..];
+
+        let encoded = encode_form(&headers, &profile, data);
+
+        assert_eq!(
+            encoded[..profile.headers_size as usize],
+            form[..profile.headers_size as usize],
+        );
+    }
+}
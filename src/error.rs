@@ -0,0 +1,71 @@
+use std::fmt;
+
+/// Errors produced while parsing a `form.m` memory image.
+#[derive(Debug)]
+pub enum FormError {
+    /// The image is smaller than the fixed headers region.
+    TooSmall { need: usize, got: usize },
+    /// A decoded header field failed an invariant check.
+    InvalidHeader {
+        index: usize,
+        field: &'static str,
+        value: u16,
+    },
+    /// The free list contains a cycle back to an already-visited header.
+    FreeListCycle { ptr: u16 },
+    /// A header is reachable from more than one free-list bucket.
+    FreeListDoubleRef { ptr: u16 },
+    /// A header within the allocated range was never written (`Unused`).
+    UnusedHeaderInUse { index: usize },
+    /// Two spans of allocated or freed memory overlap.
+    OverlappingAllocations { a: u16, b: u16 },
+    /// `asmdisc` does not point to the start of the headers array.
+    AsmdiscMismatch { expected: u16, got: u16 },
+    /// A known residual string did not match the bytes recovered for its
+    /// span.
+    ResidualMismatch {
+        start: usize,
+        offset: usize,
+        expected: u8,
+        got: u8,
+    },
+}
+
+impl fmt::Display for FormError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            FormError::TooSmall { need, got } => {
+                write!(f, "image too small: need at least {need} bytes, got {got}")
+            }
+            FormError::InvalidHeader {
+                index,
+                field,
+                value,
+            } => write!(f, "header {index}: invalid {field} ({value})"),
+            FormError::FreeListCycle { ptr } => write!(f, "free list cycle at header {ptr}"),
+            FormError::FreeListDoubleRef { ptr } => {
+                write!(f, "header {ptr} referenced multiple times in free list")
+            }
+            FormError::UnusedHeaderInUse { index } => {
+                write!(f, "header {index} is never-used within the allocated range")
+            }
+            FormError::OverlappingAllocations { a, b } => {
+                write!(f, "overlapping allocations at {a} and {b}")
+            }
+            FormError::AsmdiscMismatch { expected, got } => {
+                write!(f, "asmdisc mismatch: expected {expected}, got {got}")
+            }
+            FormError::ResidualMismatch {
+                start,
+                offset,
+                expected,
+                got,
+            } => write!(
+                f,
+                "residual string at {start} does not match at byte {offset}: expected {expected:#04x}, got {got:#04x}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for FormError {}
@@ -1,25 +1,49 @@
-use std::{
-    array,
-    fmt::{self, Write},
-    fs,
-    mem::{self, offset_of},
-};
+use std::{fmt::Write, fs};
+
+mod alloc_map;
+mod bytes;
+mod encode;
+mod error;
+mod profile;
+
+use alloc_map::AllocationMap;
+use bytes::Bytes;
+use encode::encode_form;
+use error::FormError;
+use profile::FormProfile;
 
 struct Headers {
-    headers: [Header; HEADER_COUNT],
+    headers: Vec<Header>,
     used: usize,
+    /// The chain of header indices in each `free_list` bucket, in link
+    /// order, as reconstructed by `RawHeaders::free_chains`.
+    free_chains: Vec<Vec<usize>>,
 }
 
 #[derive(Debug, PartialEq, Eq)]
 enum Header {
-    Alloc { ptr: u16, len: u16, capacity: u16 },
-    Freed { next: u16, ptr: u16, capacity: u16 },
-    Unused { next: u16 },
+    Alloc {
+        ptr: u16,
+        len: u16,
+        capacity: u16,
+        /// The raw `read` cursor, not derivable from the other fields.
+        read: u16,
+    },
+    Freed {
+        next: u16,
+        ptr: u16,
+        capacity: u16,
+        /// The raw `read` tag (either `headers_size` or `0`), not derivable
+        /// from the other fields.
+        read: u16,
+    },
+    Unused {
+        next: u16,
+    },
 }
 
 /// A four-word header for a block (V5 form6.s).
 #[derive(Clone, Debug)]
-#[repr(C)]
 struct RawHeader {
     /// W - write ptr (also used as link ptr in frlist)
     write: u16,
@@ -31,35 +55,32 @@ struct RawHeader {
     end: u16,
 }
 
+/// The byte size of one [`RawHeader`] as encoded in a `form` image.
+const RAW_HEADER_SIZE: usize = 8;
+
 /// Header portion of `.bss` in the range of V5 form6.s:hblk..headend.
-#[repr(C)]
+///
+/// Decoded field-by-field according to a [`FormProfile`], since the size of
+/// `free_list` and `headers` varies between Unix versions.
 struct RawHeaders {
     /// Pointers to free block headers (V5 form6.s:frlist).
-    free_list: [u16; 17],
+    free_list: Vec<u16>,
     /// ? (V5 form6.s:asmdisc).
     asmdisc: u16,
     /// The block headers (V5 form6.s:headers).
-    headers: [RawHeader; HEADER_COUNT],
+    headers: Vec<RawHeader>,
     pad: [u16; 2],
 }
 
-/// The size of the headers area (V5 form6.s:hsz).
-const HEADERS_SIZE: u16 = 6144;
-/// The size of the data area (V5 form6.s:datasz).
-const DATA_SIZE: u16 = 32768;
-const HEADER_COUNT: usize = (HEADERS_SIZE as usize - 36) / size_of::<RawHeader>();
-
-const _: () = assert!(size_of::<RawHeaders>() == HEADERS_SIZE as usize);
-
-fn main() {
+fn main() -> Result<(), FormError> {
+    let profile = FormProfile::V5;
     let form = fs::read("distr/form.m").unwrap();
-    let form_len = u16::try_from(form.len()).unwrap();
 
-    let headers = Headers::from_form(&form);
+    let headers = Headers::from_form(&profile, &form)?;
 
     println!("Headers:");
     for (i, header) in headers.headers[..headers.used].iter().enumerate() {
-        let ptr = RawHeader::pointer_from_index(i);
+        let ptr = RawHeader::pointer_from_index(&profile, i);
         print!("{ptr}: {header:?}");
         if let &Header::Alloc { ptr, len, .. } = header {
             let text = Bytes(&form[ptr as usize..(ptr + len) as usize]);
@@ -69,201 +90,348 @@ fn main() {
     }
     println!();
 
-    let mut allocs = Vec::new();
-    #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
-    enum State {
-        Alloc,
-        Slack,
-        KnownFreed,
-        Unknown,
-    }
+    println!("Free lists:");
+    print!("{}", headers.free_list_report(&profile));
+    println!();
 
-    for header in &headers.headers[..headers.used] {
-        match *header {
-            Header::Alloc { ptr, len, capacity } => {
-                allocs.push((ptr, ptr + len, State::Alloc));
-                if len != capacity {
-                    allocs.push((ptr + len, ptr + capacity, State::Slack));
-                }
-            }
-            Header::Freed { ptr, capacity, .. } => {
-                allocs.push((ptr, ptr + capacity, State::KnownFreed));
-            }
-            Header::Unused { .. } => unreachable!(),
-        }
-    }
-    allocs.sort();
+    let alloc_map = AllocationMap::from_headers(&headers, &form, profile.headers_size)?;
 
-    let mut prev_alloc = HEADERS_SIZE as u16;
-    for i in 0..allocs.len() {
-        let (start, end, _) = allocs[i];
-        if start > prev_alloc {
-            allocs.push((prev_alloc, start, State::Unknown));
-        }
-        if start < prev_alloc {
-            panic!("overlapping allocations");
-        }
-        prev_alloc = end;
-    }
-    if prev_alloc < form_len {
-        allocs.push((prev_alloc, form_len, State::Unknown));
+    if std::env::args().nth(1).as_deref() == Some("--json") {
+        emit_json(&alloc_map);
+        return Ok(());
     }
-    allocs.sort();
-
-    let mut freed_text = String::new();
-    let mut freed_cells = vec![None; form.len()];
 
     println!("Allocations:");
-    for &(start, end, state) in &allocs {
-        let (i, j, truncated) = if state == State::KnownFreed && end > form_len {
-            (start.min(form_len), end.min(form_len), "...")
+    for span in &alloc_map.spans {
+        let truncated = if span.bytes.len() < span.len as usize {
+            "..."
         } else {
-            (start, end, "")
-        };
-        let text = Bytes(&form[i as usize..j as usize]);
-        let len = end - start;
-        println!("offset={start}, len={len}, kind={state:?}, text={text:?}{truncated}");
-
-        write!(freed_text, "«{start}:").unwrap();
-        let short_state = match state {
-            State::Alloc => 'a',
-            State::Slack => 's',
-            State::KnownFreed => 'f',
-            State::Unknown => 'u',
+            ""
         };
-        write!(freed_text, "{short_state}»").unwrap();
-        if state == State::Alloc {
-            for _ in 0..len {
-                freed_text.push('\u{FFFD}');
-            }
-        } else {
-            write!(freed_text, "{text}").unwrap();
-        }
-
-        if state != State::Alloc {
-            for i in i..j {
-                freed_cells[i as usize] = Some(form[i as usize]);
-            }
-        }
+        let text = Bytes(&span.bytes);
+        println!(
+            "offset={}, len={}, kind={:?}, text={text:?}{truncated}",
+            span.offset, span.len, span.kind
+        );
     }
     println!();
 
     println!("Freed text:");
-    println!("{freed_text}");
+    println!("{}", alloc_map.freed_text());
 
     let residual_strings: Vec<(usize, String)> =
         serde_json::from_str(&fs::read_to_string("residual.json").unwrap()).unwrap();
-    for (start, string) in residual_strings {
-        for (i, &b) in string.as_bytes().iter().enumerate() {
-            if let Some(b2) = freed_cells[start + i]
-                && b2 != b
-            {
-                let freed_cells_str = freed_cells[start..start + string.len()]
-                    .iter()
-                    .map(|c| c.unwrap_or(b'?'))
-                    .collect::<Vec<u8>>();
-                panic!(
-                    "freed string does not match at byte {i}:\n  start = {start}\n  json =  {:?}\n  form =  {:?}\n  cells = {:?}\n",
-                    Bytes(string.as_bytes()),
-                    Bytes(&form[start..start + string.len()]),
-                    Bytes(&freed_cells_str),
-                );
-            }
-        }
+    alloc_map.check_residual_strings(&form, &residual_strings)?;
+
+    // Self-check: re-encoding the parsed headers should byte-for-byte
+    // reproduce the headers area we decoded them from.
+    let headers_size = profile.headers_size as usize;
+    let reencoded = encode_form(&headers, &profile, &form[headers_size..]);
+    if reencoded[..headers_size] != form[..headers_size] {
+        eprintln!("warning: re-encoded headers do not round-trip the original image");
     }
+
+    Ok(())
+}
+
+/// Prints the reconstructed heap as JSON for downstream diffing/visualization
+/// tools, one line via `serde_json::to_string`.
+#[cfg(feature = "serde")]
+fn emit_json(alloc_map: &AllocationMap) {
+    println!("{}", serde_json::to_string(alloc_map).unwrap());
+}
+
+#[cfg(not(feature = "serde"))]
+fn emit_json(_alloc_map: &AllocationMap) {
+    eprintln!("--json requires the `serde` feature: rebuild with --features serde");
 }
 
 impl Headers {
-    fn from_form(form: &[u8]) -> Self {
-        let raw = RawHeaders::from_form(form);
-        let mut free = [false; _];
-        for &header in &raw.free_list {
-            RawHeaders::visit_free(&mut free, &raw, header);
-        }
+    fn from_form(profile: &FormProfile, form: &[u8]) -> Result<Self, FormError> {
+        let raw = RawHeaders::from_form(profile, form)?;
+        let mut free = vec![false; profile.header_count];
+        let free_chains = raw.free_chains(profile, &mut free)?;
 
-        // Assumed invariant:
-        assert_eq!(raw.asmdisc as usize, offset_of!(RawHeaders, headers));
+        let asmdisc_expected = profile.headers_offset() as u16;
+        if raw.asmdisc != asmdisc_expected {
+            return Err(FormError::AsmdiscMismatch {
+                expected: asmdisc_expected,
+                got: raw.asmdisc,
+            });
+        }
         // Observed invariant:
-        assert_eq!(raw.pad, [0, 0]);
+        if raw.pad != [0, 0] {
+            return Err(FormError::InvalidHeader {
+                index: profile.header_count,
+                field: "pad",
+                value: raw.pad[0],
+            });
+        }
 
         let form_len = form.len().try_into().unwrap();
-        let parsed = array::from_fn(|i| {
-            Header::from_raw(&raw.headers[i], free[i], form_len).expect("invalid header")
-        });
+        let mut parsed = Vec::with_capacity(profile.header_count);
+        for (i, header) in raw.headers.iter().enumerate() {
+            parsed.push(Header::from_raw(profile, header, free[i], form_len, i)?);
+        }
 
         let mut used = parsed.len();
         if parsed[parsed.len() - 1] == (Header::Unused { next: 0 }) {
             used -= 1;
-            let mut next = RawHeader::pointer_from_index(used);
+            let mut next = RawHeader::pointer_from_index(profile, used);
             while used > 0 && parsed[used - 1] == { Header::Unused { next } } {
                 used -= 1;
-                next -= size_of::<Header>() as u16;
+                next -= RAW_HEADER_SIZE as u16;
             }
         }
-        for header in &parsed[..used] {
+        for (i, header) in parsed[..used].iter().enumerate() {
             if matches!(header, Header::Unused { .. }) {
-                panic!("never-used header within allocated headers");
+                return Err(FormError::UnusedHeaderInUse { index: i });
             }
         }
 
-        Headers {
+        Ok(Headers {
             headers: parsed,
             used,
+            free_chains,
+        })
+    }
+
+    /// Renders each free-list bucket's chain as a labeled graph: one line
+    /// per header, named `h<index>`, cross-referencing the header each `next`
+    /// link points to (mapped back to its label by
+    /// `RawHeader::index_from_pointer`).
+    fn free_list_report(&self, profile: &FormProfile) -> String {
+        let mut out = String::new();
+        for (k, chain) in self.free_chains.iter().enumerate() {
+            if chain.is_empty() {
+                continue;
+            }
+            writeln!(out, "bucket {k}:").unwrap();
+            for &i in chain {
+                let (next, capacity) = match self.headers[i] {
+                    Header::Freed { next, capacity, .. } => (next, capacity),
+                    Header::Unused { next } => (next, 0),
+                    Header::Alloc { .. } => {
+                        unreachable!("free chain references an allocated header")
+                    }
+                };
+                let next_label = if next == 0 {
+                    "nil".to_string()
+                } else {
+                    format!("h{}", RawHeader::index_from_pointer(profile, next))
+                };
+                writeln!(out, "  h{i}: capacity={capacity}, next={next_label}").unwrap();
+            }
+        }
+        out
+    }
+
+    /// Rebuilds the [`RawHeaders`] this was parsed from, the inverse of
+    /// `Headers::from_form`. Free-list bucket heads are taken from the first
+    /// link of each `free_chains` chain, and `asmdisc`/`pad` are restored to
+    /// their fixed invariant values.
+    fn to_raw(&self, profile: &FormProfile) -> RawHeaders {
+        let headers_size = profile.headers_size;
+        let headers = self
+            .headers
+            .iter()
+            .map(|header| match *header {
+                Header::Alloc {
+                    ptr,
+                    len,
+                    capacity,
+                    read,
+                } => RawHeader {
+                    write: ptr + len,
+                    read,
+                    start: ptr,
+                    end: ptr + capacity,
+                },
+                Header::Freed {
+                    next,
+                    ptr,
+                    capacity,
+                    read,
+                } => RawHeader {
+                    write: next,
+                    read,
+                    start: ptr,
+                    end: ptr + capacity,
+                },
+                Header::Unused { next } => RawHeader {
+                    write: next,
+                    read: headers_size,
+                    start: headers_size,
+                    end: headers_size,
+                },
+            })
+            .collect();
+
+        let free_list = self
+            .free_chains
+            .iter()
+            .map(|chain| match chain.first() {
+                Some(&i) => RawHeader::pointer_from_index(profile, i),
+                None => 0,
+            })
+            .collect();
+
+        RawHeaders {
+            free_list,
+            asmdisc: profile.headers_offset() as u16,
+            headers,
+            pad: [0, 0],
         }
     }
 }
 
 impl RawHeaders {
-    fn from_form(form: &[u8]) -> Self {
-        let headers: &[u8; HEADERS_SIZE as _] = form.first_chunk().unwrap();
-        unsafe { mem::transmute(*headers) }
+    fn from_form(profile: &FormProfile, form: &[u8]) -> Result<Self, FormError> {
+        if form.len() < profile.headers_size as usize {
+            return Err(FormError::TooSmall {
+                need: profile.headers_size as usize,
+                got: form.len(),
+            });
+        }
+
+        let mut off = 0;
+        let mut read_u16 = |form: &[u8]| {
+            let v = u16::from_le_bytes(form[off..off + 2].try_into().unwrap());
+            off += 2;
+            v
+        };
+        let free_list = (0..profile.free_list_len).map(|_| read_u16(form)).collect();
+        let asmdisc = read_u16(form);
+        let headers = (0..profile.header_count)
+            .map(|_| RawHeader {
+                write: read_u16(form),
+                read: read_u16(form),
+                start: read_u16(form),
+                end: read_u16(form),
+            })
+            .collect();
+        let pad = [read_u16(form), read_u16(form)];
+        debug_assert_eq!(off, profile.headers_size as usize);
+
+        Ok(RawHeaders {
+            free_list,
+            asmdisc,
+            headers,
+            pad,
+        })
+    }
+
+    /// Serializes this back to the little-endian byte layout `from_form`
+    /// decodes, the inverse of that function.
+    fn to_le_bytes(&self, profile: &FormProfile) -> Vec<u8> {
+        let mut out = Vec::with_capacity(profile.headers_size as usize);
+        for &ptr in &self.free_list {
+            out.extend_from_slice(&ptr.to_le_bytes());
+        }
+        out.extend_from_slice(&self.asmdisc.to_le_bytes());
+        for header in &self.headers {
+            out.extend_from_slice(&header.write.to_le_bytes());
+            out.extend_from_slice(&header.read.to_le_bytes());
+            out.extend_from_slice(&header.start.to_le_bytes());
+            out.extend_from_slice(&header.end.to_le_bytes());
+        }
+        for &word in &self.pad {
+            out.extend_from_slice(&word.to_le_bytes());
+        }
+        out
+    }
+
+    /// Walks every `free_list` bucket, recording the ordered chain of header
+    /// indices reachable from each `free_list[k]` and marking them in `free`.
+    fn free_chains(
+        &self,
+        profile: &FormProfile,
+        free: &mut [bool],
+    ) -> Result<Vec<Vec<usize>>, FormError> {
+        self.free_list
+            .iter()
+            .map(|&head| {
+                let mut chain = Vec::new();
+                Self::visit_free(profile, free, self, head, &mut chain)?;
+                Ok(chain)
+            })
+            .collect()
     }
 
-    fn visit_free(free: &mut [bool; HEADER_COUNT], headers: &RawHeaders, header: u16) {
+    fn visit_free(
+        profile: &FormProfile,
+        free: &mut [bool],
+        headers: &RawHeaders,
+        header: u16,
+        chain: &mut Vec<usize>,
+    ) -> Result<(), FormError> {
         if header == 0 {
-            return;
+            return Ok(());
         }
-        let i = RawHeader::index_from_pointer(header);
-        let is_free = &mut free[i];
-        if *is_free {
-            panic!("block header {header} referenced multiple times in free list");
+        let i = RawHeader::index_from_pointer(profile, header);
+        // A link back to a header already in this chain is a cycle of any
+        // length (including the direct self-loop); a link to a header freed
+        // by an earlier bucket is a double reference.
+        if chain.contains(&i) {
+            return Err(FormError::FreeListCycle { ptr: header });
         }
-        *is_free = true;
+        if free[i] {
+            return Err(FormError::FreeListDoubleRef { ptr: header });
+        }
+        free[i] = true;
+        chain.push(i);
         let next_free = headers.headers[i].write;
-        Self::visit_free(free, headers, next_free);
+        Self::visit_free(profile, free, headers, next_free, chain)
     }
 }
 
 impl Header {
-    fn from_raw(header: &RawHeader, is_free: bool, form_len: u16) -> Option<Header> {
+    fn from_raw(
+        profile: &FormProfile,
+        header: &RawHeader,
+        is_free: bool,
+        form_len: u16,
+        index: usize,
+    ) -> Result<Header, FormError> {
+        let headers_size = profile.headers_size;
+        let data_end = profile.data_base + profile.data_size;
         if is_free {
             // Observed invariants:
-            if header.start == HEADERS_SIZE && header.end == HEADERS_SIZE {
-                if header.read != HEADERS_SIZE {
-                    return None;
+            if header.start == headers_size && header.end == headers_size {
+                if header.read != headers_size {
+                    return Err(FormError::InvalidHeader {
+                        index,
+                        field: "read",
+                        value: header.read,
+                    });
                 }
-                Some(Header::Unused { next: header.write })
+                Ok(Header::Unused { next: header.write })
             } else {
                 // Observed invariants:
-                if header.start >= HEADERS_SIZE
-                    && header.end <= HEADERS_SIZE + DATA_SIZE
+                if header.start >= headers_size
+                    && header.end <= data_end
                     && (header.end - header.start).is_power_of_two()
                     && header.start <= header.end
-                    && (header.read == HEADERS_SIZE || header.read == 0)
+                    && (header.read == headers_size || header.read == 0)
                 {
-                    Some(Header::Freed {
+                    Ok(Header::Freed {
                         next: header.write,
                         ptr: header.start,
                         capacity: header.end - header.start,
+                        read: header.read,
                     })
                 } else {
-                    None
+                    Err(FormError::InvalidHeader {
+                        index,
+                        field: "start",
+                        value: header.start,
+                    })
                 }
             }
         } else {
             // Invariants from V5 form6.s:preposterous:
-            if header.start >= HEADERS_SIZE
-                && header.end <= HEADERS_SIZE + DATA_SIZE
+            if header.start >= headers_size
+                && header.end <= data_end
                 && (header.end - header.start).is_power_of_two()
                 // Observed invariants:
                 && header.start <= header.end
@@ -272,54 +440,176 @@ impl Header {
                 && (header.start..=header.end).contains(&header.write)
                 && header.read <= header.write
             {
-                Some(Header::Alloc {
+                Ok(Header::Alloc {
                     ptr: header.start,
                     len: header.write - header.start,
                     capacity: header.end - header.start,
+                    read: header.read,
                 })
             } else {
-                None
+                Err(FormError::InvalidHeader {
+                    index,
+                    field: "end",
+                    value: header.end,
+                })
             }
         }
     }
 }
 
 impl RawHeader {
-    fn index_from_pointer(ptr: u16) -> usize {
-        (ptr as usize - offset_of!(RawHeaders, headers)) / size_of::<RawHeader>()
+    fn index_from_pointer(profile: &FormProfile, ptr: u16) -> usize {
+        (ptr as usize - profile.headers_offset()) / RAW_HEADER_SIZE
     }
 
-    fn pointer_from_index(index: usize) -> u16 {
-        (offset_of!(RawHeaders, headers) + size_of::<RawHeader>() * index)
+    fn pointer_from_index(profile: &FormProfile, index: usize) -> u16 {
+        (profile.headers_offset() + RAW_HEADER_SIZE * index)
             .try_into()
             .unwrap()
     }
 }
 
-struct Bytes<'a>(&'a [u8]);
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-impl fmt::Debug for Bytes<'_> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "\"{self}\"")
+    /// A profile small enough to hand-build headers areas for: 1 free-list
+    /// bucket, 2 block headers.
+    fn small_profile() -> FormProfile {
+        FormProfile {
+            headers_size: 24,
+            data_size: 64,
+            free_list_len: 1,
+            header_count: 2,
+            data_base: 24,
+        }
+    }
+
+    /// A profile with 2 free-list buckets, for free-list tests that need
+    /// more than one bucket head.
+    fn two_bucket_profile() -> FormProfile {
+        FormProfile {
+            headers_size: 26,
+            data_size: 64,
+            free_list_len: 2,
+            header_count: 2,
+            data_base: 26,
+        }
     }
-}
 
-impl fmt::Display for Bytes<'_> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        for &b in self.0 {
-            match b {
-                b'\\' => f.write_str("\\\\"),
-                b' '..=b'~' => f.write_char(b as char),
-                b'\0' => f.write_str("\\0"),
-                // 0x08 => f.write_str("\\b"),
-                b'\t' => f.write_str("\\t"),
-                b'\n' => f.write_str("\\n"),
-                // 0x0B => f.write_str("\\v"),
-                // 0x0C => f.write_str("\\f"),
-                // 0x0D => f.write_str("\\r"),
-                b => write!(f, "\\x{b:02x}"),
-            }?;
+    /// Packs `free_list`, `asmdisc`, and `headers` (as `(write, read, start,
+    /// end)` tuples) into a little-endian image sized to `profile`.
+    fn build_image(
+        profile: &FormProfile,
+        free_list: &[u16],
+        asmdisc: u16,
+        headers: &[(u16, u16, u16, u16)],
+    ) -> Vec<u8> {
+        let mut out = Vec::new();
+        for &ptr in free_list {
+            out.extend_from_slice(&ptr.to_le_bytes());
+        }
+        out.extend_from_slice(&asmdisc.to_le_bytes());
+        for &(write, read, start, end) in headers {
+            out.extend_from_slice(&write.to_le_bytes());
+            out.extend_from_slice(&read.to_le_bytes());
+            out.extend_from_slice(&start.to_le_bytes());
+            out.extend_from_slice(&end.to_le_bytes());
         }
-        Ok(())
+        out.extend_from_slice(&0u16.to_le_bytes());
+        out.extend_from_slice(&0u16.to_le_bytes());
+        out.resize(
+            profile.headers_size as usize + profile.data_size as usize,
+            0,
+        );
+        out
+    }
+
+    #[test]
+    fn too_small_image_is_rejected() {
+        let profile = small_profile();
+        let form = vec![0u8; profile.headers_size as usize - 1];
+        assert!(matches!(
+            RawHeaders::from_form(&profile, &form),
+            Err(FormError::TooSmall { .. })
+        ));
+    }
+
+    #[test]
+    fn invalid_header_length_is_rejected() {
+        let profile = small_profile();
+        let asmdisc = profile.headers_offset() as u16;
+        // header0: start=24, end=27 is a length-3 span, not a power of two.
+        let form = build_image(&profile, &[0], asmdisc, &[(24, 24, 24, 27), (0, 0, 0, 0)]);
+        assert!(matches!(
+            Headers::from_form(&profile, &form),
+            Err(FormError::InvalidHeader { field: "end", .. })
+        ));
+    }
+
+    #[test]
+    fn free_list_cycle_longer_than_one_is_detected() {
+        let profile = small_profile();
+        let asmdisc = profile.headers_offset() as u16;
+        // h0 (ptr=4) links to h1 (ptr=12), which links back to h0.
+        let form = build_image(
+            &profile,
+            &[4],
+            asmdisc,
+            &[(12, 24, 24, 32), (4, 24, 24, 32)],
+        );
+        assert!(matches!(
+            Headers::from_form(&profile, &form),
+            Err(FormError::FreeListCycle { ptr: 4 })
+        ));
+    }
+
+    #[test]
+    fn free_list_double_ref_across_buckets_is_detected() {
+        let profile = two_bucket_profile();
+        let asmdisc = profile.headers_offset() as u16;
+        // Bucket 0 heads h0 (ptr=6, terminates). Bucket 1 heads h1 (ptr=14),
+        // which links to h0 again instead of terminating.
+        let form = build_image(
+            &profile,
+            &[6, 14],
+            asmdisc,
+            &[(0, 26, 26, 34), (6, 26, 26, 34)],
+        );
+        assert!(matches!(
+            Headers::from_form(&profile, &form),
+            Err(FormError::FreeListDoubleRef { ptr: 6 })
+        ));
+    }
+
+    #[test]
+    fn unused_header_before_tail_is_rejected() {
+        let profile = small_profile();
+        let asmdisc = profile.headers_offset() as u16;
+        // h0 (ptr=4) is the never-used sentinel, but h1 after it is a real
+        // allocation, so h0 isn't part of the trailing never-used run.
+        let form = build_image(
+            &profile,
+            &[4],
+            asmdisc,
+            &[(0, 24, 24, 24), (28, 24, 24, 32)],
+        );
+        assert!(matches!(
+            Headers::from_form(&profile, &form),
+            Err(FormError::UnusedHeaderInUse { index: 0 })
+        ));
+    }
+
+    #[test]
+    fn asmdisc_mismatch_is_rejected() {
+        let profile = small_profile();
+        let form = build_image(&profile, &[0], 999, &[(0, 0, 0, 0), (0, 0, 0, 0)]);
+        assert!(matches!(
+            Headers::from_form(&profile, &form),
+            Err(FormError::AsmdiscMismatch {
+                expected: 4,
+                got: 999
+            })
+        ));
     }
 }
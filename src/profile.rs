@@ -0,0 +1,56 @@
+/// The dimensions of a `form` headers dump that differ between Unix versions.
+///
+/// `form6.s` hardcodes `hsz`, `datasz`, and the length of `frlist` for one
+/// particular release; a [`FormProfile`] captures those dimensions so the
+/// same decoder can read dumps from other versions.
+///
+/// TODO(thaliaarchi/unix-form-read#chunk0-2): only [`FormProfile::V5`] is
+/// provided. V6/V7 support is still outstanding — it needs real `hsz`/`datasz`
+/// values confirmed against an actual dump, and likely widening every offset
+/// field from `u16` (see the note on [`FormProfile::V5`]) before a profile
+/// anywhere near 64KB of data can be addressed at all.
+#[derive(Clone, Copy, Debug)]
+pub struct FormProfile {
+    /// The size of the headers area (`hsz`).
+    pub headers_size: u16,
+    /// The size of the data area (`datasz`).
+    pub data_size: u16,
+    /// The number of free-list buckets (`frlist`).
+    pub free_list_len: usize,
+    /// The number of block headers that fit in `headers_size`.
+    pub header_count: usize,
+    /// The offset of the start of the data region, immediately after the
+    /// headers area.
+    pub data_base: u16,
+}
+
+impl FormProfile {
+    /// V5 `form6.s`.
+    ///
+    /// V6 and V7 profiles aren't included here: their `hsz`/`datasz` haven't
+    /// been verified against an actual dump, and a `data_size` anywhere near
+    /// 64KB can't be addressed by the `u16` offsets used throughout the
+    /// decoder (`RawHeader`'s fields, `Header::{Alloc,Freed}::{ptr,capacity}`)
+    /// regardless. Adding them for real requires confirmed values and,
+    /// likely, widening those offset types.
+    pub const V5: FormProfile = FormProfile::new(6144, 32768, 17);
+
+    const fn new(headers_size: u16, data_size: u16, free_list_len: usize) -> FormProfile {
+        // free_list (2 bytes each) + asmdisc (2 bytes) + headers (8 bytes
+        // each) + pad (4 bytes).
+        let overhead = free_list_len * 2 + 2 + 4;
+        let header_count = (headers_size as usize - overhead) / 8;
+        FormProfile {
+            headers_size,
+            data_size,
+            free_list_len,
+            header_count,
+            data_base: headers_size,
+        }
+    }
+
+    /// The byte offset of the first block header (after `free_list` and `asmdisc`).
+    pub fn headers_offset(&self) -> usize {
+        self.free_list_len * 2 + 2
+    }
+}